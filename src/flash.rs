@@ -25,8 +25,49 @@ EXTRA BONUS POINTS
 const FLASH_KEYR_KEY_1: u32 = 0x45670123;
 const FLASH_KEYR_KEY_2: u32 = 0xCDEF89AB;
 
-const CCM_RAM_START: u32 = 0x10000000;
-const PAGE_SZE: u32 = 0x800; // 2 KiB (2048 byte)
+/// Address of the first byte of flash memory, as seen by the core.
+const FLASH_START: u32 = 0x0800_0000;
+
+/// Size of a single flash page.
+const PAGE_SIZE: u32 = 0x800; // 2 KiB (2048 byte)
+
+/// Size of the flash memory on a given device.
+///
+/// STM32F303 variants range from 16 KiB to 512 KiB of flash, so the size
+/// can't be hard-coded and has to be supplied by the user, matching the
+/// datasheet for the part in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashSize {
+    /// 16 KiB
+    Sz16K,
+    /// 32 KiB
+    Sz32K,
+    /// 64 KiB
+    Sz64K,
+    /// 128 KiB
+    Sz128K,
+    /// 256 KiB
+    Sz256K,
+    /// 384 KiB
+    Sz384K,
+    /// 512 KiB
+    Sz512K,
+}
+
+impl FlashSize {
+    /// Size of the flash memory, in bytes.
+    pub fn bytes(self) -> u32 {
+        match self {
+            FlashSize::Sz16K => 16 * 1024,
+            FlashSize::Sz32K => 32 * 1024,
+            FlashSize::Sz64K => 64 * 1024,
+            FlashSize::Sz128K => 128 * 1024,
+            FlashSize::Sz256K => 256 * 1024,
+            FlashSize::Sz384K => 384 * 1024,
+            FlashSize::Sz512K => 512 * 1024,
+        }
+    }
+}
 
 // TODO impl std::Error for this?
 #[derive(Debug)]
@@ -37,217 +78,495 @@ pub enum FlashError {
     Busy,
     /// Could not erase the desired Page
     EraseFailed,
+    /// Could not program the desired half-word
+    ProgrammingFailed,
     /// Could not unlock Flash for Erasing/Writing
     UnlockFailed,
+    /// A half-word read back after programming did not match what was written
+    VerifyError,
+    /// The requested address (or the end of the requested range) lies beyond
+    /// the end of flash memory, or the requested range starts below the
+    /// start of flash memory or ends before it starts
+    AddressLargerThanFlash,
+    /// The address is not properly aligned for the operation: half-word
+    /// writes need to be 2-byte aligned, page erases need to be page-aligned
+    AddressMisaligned,
+    /// The data to write is not a multiple of 2 bytes (one half-word)
+    LengthNotMultiple2,
+    /// The requested operation is longer than the flash memory itself
+    LengthTooLong,
+    /// The target page is write-protected (`SR.WRPRTERR`)
+    WriteProtected,
+}
+
+/// Turn a page number into the absolute flash address of its first byte.
+pub const fn page_address(page: u16) -> u32 {
+    FLASH_START + page as u32 * PAGE_SIZE
+}
+
+/// Turn an absolute flash address into the number of the page it falls into.
+/// The inverse of [`page_address`].
+pub const fn address_to_page(address: u32) -> u16 {
+    ((address - FLASH_START) / PAGE_SIZE) as u16
 }
 
 /// Extension trait to constrain the FLASH peripheral
 pub trait FlashExt {
     /// Constrains the FLASH peripheral to play nicely with the other abstractions
     fn constrain(self) -> Parts;
-
-    /// Erase Flash Page at `address`.
-    /// Note that one page = 2KByte
-    ///
-    /// ⚠️⚠️⚠️ CAUTION: ⚠️⚠️⚠️
-    /// This function does *not* perform any bounds checks.
-    /// If you erase program code, that is on you.
-    fn page_erase(self, address: u32) -> Result<(), FlashError>;
-
-    /// Write to Flash Page.
-    /// Note that one page = 2KByte
-    ///
-    /// ⚠️⚠️⚠️ CAUTION: ⚠️⚠️⚠️
-    /// This function does *not* perform any bounds checks.
-    /// If you overwrite program code, that is on you.
-    fn page_write(self, address: u32, data: u32) -> Result<(), FlashError>;
 }
 
 impl FlashExt for FLASH {
     fn constrain(self) -> Parts {
         Parts {
             acr: ACR { _0: () },
+            ar: AR { _0: () },
+            cr: CR { _0: () },
+            keyr: KEYR { _0: () },
+            sr: SR { _0: () },
         }
     }
+}
+
+/// Constrained FLASH peripheral
+pub struct Parts {
+    /// Opaque Access Control Register (ACR)
+    pub acr: ACR,
+    /// Opaque Address Register (AR)
+    pub(crate) ar: AR,
+    /// Opaque Control Register (CR)
+    pub(crate) cr: CR,
+    /// Opaque Key Register (KEYR)
+    pub(crate) keyr: KEYR,
+    /// Opaque Status Register (SR)
+    pub(crate) sr: SR,
+}
 
-    fn page_erase(self, address: u32) -> Result<(), FlashError> {
-        // 1. Check that no main Flash memory operation is ongoing by checking the BSY bit in
-        //    the FLASH_SR register.
-        if self.sr.read().bsy().bit_is_set() {
-            // TODO alternatively wait until we can erase
-            // We are busy! Come back later
-            return Err(FlashError::Busy);
+impl Parts {
+    /// Obtain a [`FlashWriter`] borrowing these constrained registers.
+    ///
+    /// The returned writer only borrows the FLASH registers, so they stay
+    /// accessible across many operations instead of being consumed by one.
+    ///
+    /// If `verify` is `true`, every programmed half-word is read back and
+    /// checked, returning [`FlashError::VerifyError`] on a mismatch.
+    ///
+    /// `flash_size` must match the flash size of the part in use: every
+    /// operation on the returned writer is bounds-checked against it.
+    pub fn writer(&mut self, flash_size: FlashSize, verify: bool) -> FlashWriter {
+        FlashWriter {
+            ar: &mut self.ar,
+            cr: &mut self.cr,
+            keyr: &mut self.keyr,
+            sr: &mut self.sr,
+            flash_size,
+            verify,
         }
+    }
+}
 
-        // TODO is the order correct here?
-        if self.cr.read().lock().bit_is_set() {
-            defmt::info!("CR_LOCK was set, unlocking...");
-            unlock_cr(&self);
+/// Opaque Access Control Register (ACR)
+pub struct ACR {
+    _0: (),
+}
 
-            if self.cr.read().lock().bit_is_set() {
-                return Err(FlashError::UnlockFailed);
-            }
-        }
+impl ACR {
+    pub(crate) fn acr(&mut self) -> &flash::ACR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).acr }
+    }
+}
 
-        // 2. Set the PER bit in the FLASH_CR register
-        self.cr.modify(|_r, w| w.per().set_bit());
+/// Opaque Address Register (AR)
+pub struct AR {
+    _0: (),
+}
+
+impl AR {
+    fn ar(&mut self) -> &flash::AR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).ar }
+    }
+}
+
+/// Opaque Control Register (CR)
+pub struct CR {
+    _0: (),
+}
+
+impl CR {
+    fn cr(&mut self) -> &flash::CR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).cr }
+    }
+}
+
+/// Opaque Key Register (KEYR)
+pub struct KEYR {
+    _0: (),
+}
+
+impl KEYR {
+    fn keyr(&mut self) -> &flash::KEYR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).keyr }
+    }
+}
 
-        // 3. Program the FLASH_AR register to select a page to erase
-        // (this register is write-only, hence the use of `write()`)
-        self.ar.write(|w| unsafe { w.bits(address) });
+/// Opaque Status Register (SR)
+pub struct SR {
+    _0: (),
+}
 
-        // 4. Set the STRT bit in the FLASH_CR register (see below note)
-        // TODO: this is where we get
+impl SR {
+    fn sr(&mut self) -> &flash::SR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).sr }
+    }
+}
 
-        // Error: Error communicating with probe: An error with the usage of the probe occured
-        // Caused by:
-        // 0: An error with the usage of the probe occured
-        // 1: An error specific to a probe type occured
-        // 2: Command failed with status SwdDpWait
-        self.cr.modify(|_r, w| w.strt().set_bit());
+/// A handle to the FLASH peripheral, borrowed from [`Parts`], used to erase,
+/// program and read the internal flash memory.
+///
+/// Erasing and writing are performed relative to `offset`, a byte offset
+/// from the start of flash memory (`0x0800_0000`), rather than an absolute
+/// address. The one exception is [`FlashWriter::erase_range`], which takes
+/// absolute addresses.
+pub struct FlashWriter<'a> {
+    ar: &'a mut AR,
+    cr: &'a mut CR,
+    keyr: &'a mut KEYR,
+    sr: &'a mut SR,
+    flash_size: FlashSize,
+    verify: bool,
+}
 
-        // 5. Wait for the BSY bit to be reset
-        while self.sr.read().bsy().bit_is_set() {
-            // do nothing while the BSY bit is not reset yet
+impl<'a> FlashWriter<'a> {
+    /// Check that `[offset, offset + length)` falls within flash memory.
+    fn check_bounds(&self, offset: u32, length: u32) -> Result<(), FlashError> {
+        if length > self.flash_size.bytes() {
+            return Err(FlashError::LengthTooLong);
+        }
+        if offset
+            .checked_add(length)
+            .map_or(true, |end| end > self.flash_size.bytes())
+        {
+            return Err(FlashError::AddressLargerThanFlash);
+        }
+        Ok(())
+    }
+
+    fn wait_busy(&mut self) {
+        while self.sr.sr().read().bsy().bit_is_set() {
             asm::nop();
         }
-        defmt::info!("BSY bit status: {}", self.sr.read().bsy().bit());
+    }
 
-        defmt::info!("sr.WRPRTERR status: {}", self.sr.read().wrprterr().bit());
+    /// Unlock the flash control register, if it isn't already unlocked.
+    ///
+    /// Writing the key sequence to `KEYR` while the bank is already unlocked
+    /// triggers a HardFault, so this only emits it when `CR.LOCK` is set.
+    pub fn unlock(&mut self) -> Result<(), FlashError> {
+        if self.cr.cr().read().lock().bit_is_set() {
+            self.keyr.keyr().write(|w| w.fkeyr().bits(FLASH_KEYR_KEY_1));
+            self.keyr.keyr().write(|w| w.fkeyr().bits(FLASH_KEYR_KEY_2));
+
+            if self.cr.cr().read().lock().bit_is_set() {
+                return Err(FlashError::UnlockFailed);
+            }
+        }
+        Ok(())
+    }
 
-        // stolen form libopencm flash impl: reset PER bit
-        //self.cr.modify(|_r, w| w.per().clear_bit());
+    /// Lock the flash control register, if it isn't already locked.
+    pub fn lock(&mut self) {
+        if !self.cr.cr().read().lock().bit_is_set() {
+            self.cr.cr().modify(|_, w| w.lock().set_bit());
+        }
+    }
 
-        // 6. Check the EOP flag in the FLASH_SR register (it is set when the erase operation has succeeded),
-        //    and then clear it by software.
-        if self.sr.read().eop().bit_is_set() {
-            // erase was successful
-            // 7. Clear the EOP flag.
-            self.sr.modify(|_r, w| w.eop().clear_bit())
+    /// Check the `EOP`/`WRPRTERR` flags after an erase or programming
+    /// operation and clear them. `on_failure` is returned if neither flag
+    /// indicates what happened, and should describe the operation that was
+    /// attempted (erase vs. programming) so callers don't get a misleading
+    /// error.
+    fn clear_eop(&mut self, on_failure: FlashError) -> Result<(), FlashError> {
+        if self.sr.sr().read().wrprterr().bit_is_set() {
+            self.sr.sr().modify(|_, w| w.wrprterr().clear_bit());
+            return Err(FlashError::WriteProtected);
+        }
+
+        if self.sr.sr().read().eop().bit_is_set() {
+            self.sr.sr().modify(|_, w| w.eop().clear_bit());
+            Ok(())
         } else {
-            // this should be set by now!
-            return Err(FlashError::EraseFailed);
+            Err(on_failure)
         }
-        for _ in 0..10 {
-            cortex_m::asm::nop();
+    }
+
+    /// Erase a single flash page at `page_offset`, which must already be
+    /// page-aligned. Assumes flash is already unlocked.
+    fn erase_page(&mut self, page_offset: u32) -> Result<(), FlashError> {
+        self.cr.cr().modify(|_, w| w.per().set_bit());
+        self.ar.ar().write(|w| unsafe { w.bits(FLASH_START + page_offset) });
+        self.cr.cr().modify(|_, w| w.strt().set_bit());
+
+        self.wait_busy();
+        // Clear PER regardless of the outcome, so a WRPRTERR/EOP failure
+        // doesn't leave the control register in erase mode.
+        let result = self.clear_eop(FlashError::EraseFailed);
+        self.cr.cr().modify(|_, w| w.per().clear_bit());
+
+        result
+    }
+
+    /// Erase every flash page that `[offset, offset + length)` touches.
+    /// `offset` must be page-aligned.
+    ///
+    /// Note that one page is 2 KiB, so this may erase bytes outside of
+    /// `[offset, offset + length)`.
+    pub fn erase(&mut self, offset: u32, length: usize) -> Result<(), FlashError> {
+        if offset % PAGE_SIZE != 0 {
+            return Err(FlashError::AddressMisaligned);
+        }
+        if length == 0 {
+            return Ok(());
         }
-        // The software should start checking if the BSY bit equals ‘0’ at least one CPU cycle after setting the STRT bit.
-        defmt::info!(
-            "BSY bit status after address write: {}",
-            self.sr.read().bsy().bit()
-        );
-        while self.sr.read().bsy().bit_is_set() {
-            
+        self.check_bounds(offset, length as u32)?;
+        self.wait_busy();
+
+        let mut this = UnlockGuard::new(self)?;
+
+        let first_page = (offset / PAGE_SIZE) * PAGE_SIZE;
+        let last_page = ((offset + length as u32 - 1) / PAGE_SIZE) * PAGE_SIZE;
+
+        let mut page = first_page;
+        while page <= last_page {
+            this.erase_page(page)?;
+            page += PAGE_SIZE;
         }
 
         Ok(())
-        // // WE ARE ASSUMING that the above takes > cycle so we're not waiting explicitly (danger danger)
-        // if self.sr.read().bsy().bit_is_set() {
-        //     Ok(())
-        // } else {
-        //     Err(FlashError::Busy)
-        // }
     }
 
-    // TODO finish implementation
-    fn page_write(self, address: u32, data: u32) -> Result<(), FlashError> {
-        // TODO: do we have to unlock write protection (see "Unlocking the Flash memory")?
+    /// Erase every page in `[start_addr, end_addr)`, two absolute flash
+    /// addresses (as opposed to the byte offsets used elsewhere on
+    /// [`FlashWriter`]). Both must be page-aligned; see [`page_address`].
+    pub fn erase_range(&mut self, start_addr: u32, end_addr: u32) -> Result<(), FlashError> {
+        if start_addr < FLASH_START || end_addr < start_addr {
+            return Err(FlashError::AddressLargerThanFlash);
+        }
+        if start_addr % PAGE_SIZE != 0 || end_addr % PAGE_SIZE != 0 {
+            return Err(FlashError::AddressMisaligned);
+        }
+        if start_addr == end_addr {
+            return Ok(());
+        }
+        self.check_bounds(start_addr - FLASH_START, end_addr - start_addr)?;
+        self.wait_busy();
+
+        let mut this = UnlockGuard::new(self)?;
 
-        // 1. Check that no main Flash memory operation is ongoing by checking the BSY bit in
-        //    the FLASH_SR register.
-        if self.sr.read().bsy().bit_is_set() {
-            // We are busy! Come back later
-            // TODO proper error tyoe
-            return Err(FlashError::Busy);
+        let mut addr = start_addr;
+        while addr < end_addr {
+            this.erase_page(addr - FLASH_START)?;
+            addr += PAGE_SIZE;
         }
 
-        // TODO is the order correct here?
-        unlock_cr(&self);
+        Ok(())
+    }
 
-        // 2. Set the PG bit in the FLASH_CR register.
-        self.cr.write(|w| w.pg().bit(true));
+    /// Erase the entire main flash memory.
+    pub fn mass_erase(&mut self) -> Result<(), FlashError> {
+        self.wait_busy();
 
-        // 3. Perform the data write (half-word) at the desired address.
-        self.ar.write(|w| unsafe { w.bits(address) });
+        let mut this = UnlockGuard::new(self)?;
 
-        // dummy code
-        unsafe {
-            // for hword in data {
-                core::ptr::write_volatile(address as *mut u32, data as u32);
-            // }
-        }
+        this.cr.cr().modify(|_, w| w.mer().set_bit());
+        this.cr.cr().modify(|_, w| w.strt().set_bit());
 
-        // 4. Wait until the BSY bit is reset in the FLASH_SR register.
-        // 5. Check the EOP flag in the FLASH_SR register (it is set when the programming operation
-        //    has succeeded), and then clear it by software.
+        this.wait_busy();
+        // Clear MER regardless of the outcome, so a WRPRTERR/EOP failure
+        // doesn't leave the control register in erase mode.
+        let result = this.clear_eop(FlashError::EraseFailed);
+        this.cr.cr().modify(|_, w| w.mer().clear_bit());
 
-        // Copied from page erase, might need fixing
-        // 5. Wait for the BSY bit to be reset
-        while self.sr.read().bsy().bit_is_set() {
-            // do nothing while the BSY bit is not reset yet
-            asm::nop();
+        result
+    }
+
+    /// Program `data` starting at `offset`, a byte offset from the start of
+    /// flash memory.
+    ///
+    /// The STM32F3 can only program flash in 16-bit half-words, so `data` is
+    /// split into half-words and written to consecutive addresses. `data`
+    /// must therefore have an even length.
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        if offset % 2 != 0 {
+            return Err(FlashError::AddressMisaligned);
+        }
+        if data.len() % 2 != 0 {
+            return Err(FlashError::LengthNotMultiple2);
         }
-        defmt::info!("BSY bit status: {}", self.sr.read().bsy().bit());
+        self.check_bounds(offset, data.len() as u32)?;
+        self.wait_busy();
 
-        defmt::info!("sr.WRPRTERR status: {}", self.sr.read().wrprterr().bit());
+        let mut this = UnlockGuard::new(self)?;
 
-        // stolen form libopencm flash impl: reset PER bit
-        //self.cr.modify(|_r, w| w.per().clear_bit());
+        for (i, chunk) in data.chunks_exact(2).enumerate() {
+            let half_word = u16::from_le_bytes([chunk[0], chunk[1]]);
+            let address = FLASH_START + offset + 2 * i as u32;
 
-        // 6. Check the EOP flag in the FLASH_SR register (it is set when the erase operation has succeeded),
-        //    and then clear it by software.
-        if self.sr.read().eop().bit_is_set() {
-            // erase was successful
-            // 7. Clear the EOP flag.
-            self.sr.modify(|_r, w| w.eop().clear_bit())
-        } else {
-            // this should be set by now!
-            return Err(FlashError::EraseFailed);
-        }
-        for _ in 0..10 {
-            cortex_m::asm::nop();
+            this.cr.cr().modify(|_, w| w.pg().set_bit());
+            unsafe {
+                core::ptr::write_volatile(address as *mut u16, half_word);
+            }
+
+            this.wait_busy();
+            // Clear PG regardless of the outcome, so a WRPRTERR/EOP failure
+            // doesn't leave the control register in programming mode.
+            let result = this.clear_eop(FlashError::ProgrammingFailed);
+            this.cr.cr().modify(|_, w| w.pg().clear_bit());
+            result?;
+
+            if this.verify {
+                let written = unsafe { core::ptr::read_volatile(address as *const u16) };
+                if written != half_word {
+                    return Err(FlashError::VerifyError);
+                }
+            }
         }
-        // The software should start checking if the BSY bit equals ‘0’ at least one CPU cycle after setting the STRT bit.
-        defmt::info!(
-            "BSY bit status after address write: {}",
-            self.sr.read().bsy().bit()
-        );
-        while self.sr.read().bsy().bit_is_set() {
+
+        Ok(())
+    }
+
+    /// Read `length` bytes of flash memory starting at `offset`, a byte
+    /// offset from the start of flash memory.
+    ///
+    /// Flash memory is memory-mapped, so this is a plain slice over it.
+    pub fn read(&self, offset: u32, length: usize) -> Result<&[u8], FlashError> {
+        self.check_bounds(offset, length as u32)?;
+
+        let address = (FLASH_START + offset) as *const u8;
+        Ok(unsafe { core::slice::from_raw_parts(address, length) })
+    }
+
+    /// Write `data` at `offset`, preserving the rest of every page it
+    /// touches.
+    ///
+    /// `erase`s the underlying page(s) before programming, so unlike calling
+    /// [`Self::erase`] followed by [`Self::write`] directly, this does not
+    /// wipe out the bytes of the page(s) that are not part of `data`. Neither
+    /// `offset` nor `data.len()` need to be page-aligned; a write that spans
+    /// more than one page is handled page by page.
+    pub fn erase_write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        self.check_bounds(offset, data.len() as u32)?;
+
+        let mut page_start = (offset / PAGE_SIZE) * PAGE_SIZE;
+        let mut written = 0usize;
+
+        while written < data.len() {
+            let page_end = page_start + PAGE_SIZE;
+            let chunk_start = offset.max(page_start);
+            let chunk_end = (offset + data.len() as u32).min(page_end);
+            let chunk_len = (chunk_end - chunk_start) as usize;
+
+            let mut buffer = [0u8; PAGE_SIZE as usize];
+            buffer.copy_from_slice(self.read(page_start, PAGE_SIZE as usize)?);
+
+            let buf_offset = (chunk_start - page_start) as usize;
+            buffer[buf_offset..buf_offset + chunk_len]
+                .copy_from_slice(&data[written..written + chunk_len]);
+
+            self.erase(page_start, PAGE_SIZE as usize)?;
+            self.write(page_start, &buffer)?;
+
+            written += chunk_len;
+            page_start += PAGE_SIZE;
         }
 
         Ok(())
     }
 }
 
-/// An unlocking sequence should be written to the FLASH_KEYR register to open the access to
-/// the FLASH_CR register. This sequence consists of two write operations into FLASH_KEYR register:
-/// 1. Write KEY1 = 0x45670123
-/// 2. Write KEY2 = 0xCDEF89AB
-/// Any wrong sequence locks up the FPEC and the FLASH_CR register until the next reset.
-fn unlock_cr(flash: &FLASH) {
-    flash.keyr.write(|w| w.fkeyr().bits(FLASH_KEYR_KEY_1));
-    flash.keyr.write(|w| w.fkeyr().bits(FLASH_KEYR_KEY_2));
+/// Unlocks a [`FlashWriter`] on construction and re-locks it on drop, so the
+/// flash is never left writable after an operation panics or early-returns.
+struct UnlockGuard<'a, 'b> {
+    writer: &'b mut FlashWriter<'a>,
 }
 
-fn page_to_address() -> u32 {
-    // how to get to other pages? multiply page size?
-    CCM_RAM_START - PAGE_SZE
+impl<'a, 'b> UnlockGuard<'a, 'b> {
+    fn new(writer: &'b mut FlashWriter<'a>) -> Result<Self, FlashError> {
+        writer.unlock()?;
+        Ok(Self { writer })
     }
+}
 
-/// Constrained FLASH peripheral
-pub struct Parts {
-    /// Opaque Access Control Register (ACR)
-    pub acr: ACR,
+impl<'a, 'b> core::ops::Deref for UnlockGuard<'a, 'b> {
+    type Target = FlashWriter<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.writer
+    }
 }
 
-/// Opaque Access Control Register (ACR)
-pub struct ACR {
-    _0: (),
+impl<'a, 'b> core::ops::DerefMut for UnlockGuard<'a, 'b> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.writer
+    }
 }
 
-impl ACR {
-    pub(crate) fn acr(&mut self) -> &flash::ACR {
-        // NOTE(unsafe) this proxy grants exclusive access to this register
-        unsafe { &(*FLASH::ptr()).acr }
+impl<'a, 'b> Drop for UnlockGuard<'a, 'b> {
+    fn drop(&mut self) {
+        self.writer.lock();
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+mod embedded_storage_impl {
+    use super::{FlashError, FlashWriter, PAGE_SIZE};
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    impl NorFlashError for FlashError {
+        fn kind(&self) -> NorFlashErrorKind {
+            match self {
+                FlashError::AddressLargerThanFlash | FlashError::LengthTooLong => {
+                    NorFlashErrorKind::OutOfBounds
+                }
+                FlashError::AddressMisaligned => NorFlashErrorKind::NotAligned,
+                _ => NorFlashErrorKind::Other,
+            }
+        }
+    }
+
+    impl<'a> ErrorType for FlashWriter<'a> {
+        type Error = FlashError;
+    }
+
+    impl<'a> ReadNorFlash for FlashWriter<'a> {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.copy_from_slice(FlashWriter::read(self, offset, bytes.len())?);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.flash_size.bytes() as usize
+        }
+    }
+
+    impl<'a> NorFlash for FlashWriter<'a> {
+        const WRITE_SIZE: usize = 2;
+        const ERASE_SIZE: usize = PAGE_SIZE as usize;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            FlashWriter::erase(self, from, (to - from) as usize)
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            FlashWriter::write(self, offset, bytes)
+        }
     }
 }